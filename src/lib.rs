@@ -1,84 +1,416 @@
 use std::{collections::HashMap, num::ParseIntError};
 use thiserror::Error;
 
-pub fn parse_icc(icc: &str) -> Result<HashMap<String, String>, IccError> {
-    let tag_data = get_tag_data();
-    let mut i = 0_usize;
-    let mut output_data = HashMap::new();
-    while i < icc.len() {
-        let start_of_element = i;
-        let mut tag: String;
-        let first_tag_byte = &icc[i..i+2];
-        let first_tag_bits = usize::from_str_radix(first_tag_byte, 16)?;
-        let tag = if first_tag_bits & 31 == 31 { // check that bit5 -> bit1 are set to 1
-            let second_tag_byte = &icc[i+2..i+4];
-            format!("{}{}", first_tag_byte, second_tag_byte)
+/// A decoded TLV value: either a primitive (hex-encoded) leaf, or a
+/// constructed data object whose value is itself a sequence of nested
+/// TLV objects.
+#[derive(Debug, Clone, PartialEq)]
+pub enum IccValue {
+    Primitive(String),
+    Constructed(Vec<(String, IccValue)>),
+}
+
+impl IccValue {
+    /// Decodes a primitive value according to its tag's declared format.
+    /// Returns `None` for constructed values, which have no single typed
+    /// representation of their own.
+    pub fn decoded(&self, tag_data: &TagData) -> Option<String> {
+        match self {
+            IccValue::Primitive(hex) => Some(decode_formatted_value(tag_data.format, hex)),
+            IccValue::Constructed(_) => None,
+        }
+    }
+}
+
+/// Maximum nesting depth allowed when recursing into constructed tags.
+/// Guards against malformed/adversarial input nesting templates deep
+/// enough to blow the stack.
+const MAX_DEPTH: usize = 32;
+
+/// Tracks recursion depth while decoding nested constructed tags.
+struct DecodeState {
+    depth: usize,
+}
+
+impl DecodeState {
+    fn new() -> Self {
+        Self { depth: 0 }
+    }
+
+    fn enter(&mut self) -> Result<(), IccError> {
+        self.depth += 1;
+        if self.depth > MAX_DEPTH {
+            return Err(IccError::MaxDepthExceeded);
+        }
+        Ok(())
+    }
+
+    fn exit(&mut self) {
+        self.depth -= 1;
+    }
+}
+
+/// Lazily iterates the top-level BER-TLV data objects in a raw byte
+/// buffer, one `next()` call at a time. It decodes just enough of the
+/// tag and length headers to find each value's boundary and slices it
+/// straight out of the input, without building a map or copying the
+/// whole message up front - the same shape as a spec-agnostic EBML tag
+/// walker. Callers that only need the first few objects, or that want
+/// to bail out early on an oversized message, can stop consuming at
+/// any point.
+pub struct TlvIterator<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> TlvIterator<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Self { data, pos: 0 }
+    }
+}
+
+impl<'a> Iterator for TlvIterator<'a> {
+    type Item = Result<(String, &'a [u8]), IccError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.pos >= self.data.len() {
+            return None;
+        }
+        let element = self.next_element();
+        if element.is_err() {
+            // stop advancing so a caller that doesn't short-circuit on
+            // `Err` (e.g. a bare `for` loop or `.collect()`) still
+            // terminates instead of seeing this same error forever
+            self.pos = self.data.len();
+        }
+        Some(element)
+    }
+}
+
+impl<'a> TlvIterator<'a> {
+    fn next_element(&mut self) -> Result<(String, &'a [u8]), IccError> {
+        let first_tag_byte = *self.data.get(self.pos).ok_or(IccError::UnexpectedEof)?;
+        let tag_bytes = if first_tag_byte & 31 == 31 { // check that bit5 -> bit1 are set to 1
+            self.data.get(self.pos..self.pos + 2).ok_or(IccError::UnexpectedEof)?
         } else {
-            first_tag_byte.into()
+            &self.data[self.pos..self.pos + 1]
         };
-        let tag_data = match tag_data.get(&tag.to_uppercase()) {
+        let tag = tag_bytes.iter().map(|b| format!("{:02X}", b)).collect::<String>();
+        self.pos += tag_bytes.len(); // move pointer to length encoding
+
+        let (value_length, shift_pointer) = unhexify_length(&self.data[self.pos..])?;
+        self.pos += shift_pointer;
+
+        let value_end = self.pos.checked_add(value_length).ok_or(IccError::UnexpectedEof)?;
+        if value_end > self.data.len() {
+            return Err(IccError::UnexpectedEof);
+        }
+        let value = &self.data[self.pos..value_end];
+        self.pos = value_end;
+
+        Ok((tag, value))
+    }
+}
+
+/// Parses `icc` into its TLV elements in wire order. Repeated tags are
+/// not deduplicated - every occurrence is returned, in the position it
+/// appeared - so callers can see duplicate or positionally-significant
+/// objects rather than having an earlier one silently dropped. Use
+/// [`first`] or [`last`] to pick a single value for a tag that is not
+/// expected to repeat.
+pub fn parse_icc(icc: &str, dictionary: &TagDictionary) -> Result<Vec<(String, IccValue)>, IccError> {
+    let mut state = DecodeState::new();
+    let bytes = decode_hex(icc)?;
+    parse_elements(&bytes, dictionary, &mut state)
+}
+
+fn parse_elements(
+    icc: &[u8],
+    dictionary: &TagDictionary,
+    state: &mut DecodeState,
+) -> Result<Vec<(String, IccValue)>, IccError> {
+    let mut output_data = Vec::new();
+    for element in TlvIterator::new(icc) {
+        let (tag, value) = element?;
+        let tag_entry = match dictionary.get(&tag) {
             Some(tag_data) => tag_data,
-            None => return Err(IccError::BadTag(tag.into())) // don't recognise the tag
+            None => return Err(IccError::BadTag(tag)) // don't recognise the tag
         };
-        i += tag.len(); // move pointer to length encoding
-        let (value_byte_length, shift_pointer) = unhexify_length(&icc[i..])?;
-        i += shift_pointer;
-        let value_string_length = value_byte_length * 2;
-        let value_bytes: String = icc[i..i+value_string_length].to_uppercase().to_string();
-        if value_bytes.len() / 2 != value_byte_length {
-            return Err(IccError::GenericError);
-        }
-        //  check that string is hex here
-        if value_bytes.len() / 2 > tag_data.max_length {
-            return Err(IccError::GenericError); // too big for max length
+        if value.len() < tag_entry.min_length || value.len() > tag_entry.max_length {
+            return Err(IccError::BadLength(format!(
+                "tag {tag} value is {} bytes, expected between {} and {}",
+                value.len(), tag_entry.min_length, tag_entry.max_length,
+            )));
         }
-        output_data.insert(tag_data.name.clone(), value_bytes);
-        i += value_string_length // go to next tag
+
+        // bit 6 of the first tag byte marks a constructed (template) object,
+        // whose value is itself a sequence of nested TLV objects
+        let first_tag_byte = u8::from_str_radix(&tag[..2], 16)?;
+        let is_constructed = first_tag_byte & 0x20 != 0;
+        let decoded_value = if is_constructed {
+            state.enter()?;
+            let nested = parse_elements(value, dictionary, state)?;
+            state.exit();
+            IccValue::Constructed(nested)
+        } else {
+            let hex_value = value.iter().map(|b| format!("{:02X}", b)).collect::<String>();
+            IccValue::Primitive(hex_value)
+        };
+        output_data.push((tag_entry.name.clone(), decoded_value));
     }
     Ok(output_data)
 }
 
-fn unhexify_length(slice: &str) -> Result<(usize, usize), IccError> {
-    let mut length_in_bytes = 0_usize;
-    let mut shift_pointer = 2;
-    if slice.len() < 1 {
-        return Err(IccError::BadLength("length is missing for this tag!".into()));
+/// Returns the value of the first element with the given tag name, in
+/// wire order.
+pub fn first<'a>(data: &'a [(String, IccValue)], name: &str) -> Option<&'a IccValue> {
+    data.iter().find(|(n, _)| n == name).map(|(_, v)| v)
+}
+
+/// Returns the value of the last element with the given tag name, in
+/// wire order - this is the value a `HashMap`-based lookup would have
+/// silently kept, since each later occurrence overwrote the one before it.
+pub fn last<'a>(data: &'a [(String, IccValue)], name: &str) -> Option<&'a IccValue> {
+    data.iter().rev().find(|(n, _)| n == name).map(|(_, v)| v)
+}
+
+/// Encodes a sequence of tag -> value pairs into a BER-TLV hex string -
+/// the inverse of [`parse_icc`]. Each key may be either a wire tag (e.g.
+/// `"9F33"`) or a dictionary name (e.g. `"terminalcapabilities"`) - see
+/// [`TagDictionary::resolve`] - so the output of `parse_icc`, which keys
+/// elements by name, can be fed straight back in. Elements are encoded
+/// in the order given, so round-tripping `parse_icc`'s output faithfully
+/// reproduces repeated tags in their original positions.
+pub fn encode_icc(data: &[(String, IccValue)], dictionary: &TagDictionary) -> Result<String, IccError> {
+    let mut encoded = String::new();
+    for (key, value) in data {
+        encoded.push_str(&encode_element(key, value, dictionary)?);
+    }
+    Ok(encoded)
+}
+
+fn encode_element(key: &str, value: &IccValue, dictionary: &TagDictionary) -> Result<String, IccError> {
+    let tag_entry = dictionary.resolve(key).ok_or_else(|| IccError::BadTag(key.to_owned()))?;
+    let tag = tag_entry.tag.clone();
+    let value_hex = match value {
+        IccValue::Primitive(hex) => {
+            if !hex.len().is_multiple_of(2) {
+                return Err(IccError::OddLengthHex);
+            }
+            hex.to_uppercase()
+        },
+        IccValue::Constructed(children) => {
+            let mut inner = String::new();
+            for (child_key, child_value) in children {
+                inner.push_str(&encode_element(child_key, child_value, dictionary)?);
+            }
+            inner
+        }
+    };
+    let value_byte_length = value_hex.len() / 2;
+    if value_byte_length < tag_entry.min_length || value_byte_length > tag_entry.max_length {
+        return Err(IccError::BadLength(format!(
+            "value for tag {tag} is {value_byte_length} bytes, expected between {} and {}",
+            tag_entry.min_length, tag_entry.max_length,
+        )));
+    }
+    Ok(format!("{}{}{}", tag, encode_length(value_byte_length), value_hex))
+}
+
+/// Encodes a byte length using BER-TLV definite-length form: a single
+/// byte for values < 128, otherwise `0x80 | n` followed by the minimal
+/// `n` big-endian length bytes needed to represent it.
+fn encode_length(length_in_bytes: usize) -> String {
+    if length_in_bytes < 128 {
+        return format!("{:02X}", length_in_bytes);
+    }
+    let mut length_bytes = Vec::new();
+    let mut remaining = length_in_bytes;
+    while remaining > 0 {
+        length_bytes.push((remaining & 0xFF) as u8);
+        remaining >>= 8;
+    }
+    length_bytes.reverse();
+    let mut encoded = format!("{:02X}", 0x80 | length_bytes.len() as u8);
+    for byte in length_bytes {
+        encoded.push_str(&format!("{:02X}", byte));
+    }
+    encoded
+}
+
+/// Decodes a hex string into bytes up front, so the rest of the parser
+/// works entirely on a byte buffer instead of re-hexing 2-char string
+/// windows. Non-ASCII input is rejected before any indexing happens, so
+/// a malformed tag never causes an out-of-char-boundary slice panic.
+fn decode_hex(icc: &str) -> Result<Vec<u8>, IccError> {
+    if !icc.is_ascii() {
+        return Err(IccError::BadLength("hex string must be ASCII".into()));
+    }
+    if !icc.len().is_multiple_of(2) {
+        return Err(IccError::OddLengthHex);
     }
-    let first_length_byte = usize::from_str_radix(&slice[..2], 16)?;
+    (0..icc.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&icc[i..i+2], 16).map_err(IccError::from))
+        .collect()
+}
+
+/// Decodes a hex value according to its tag's format. Numeric and
+/// compressed-numeric tags are stored as BCD, right-padded with `F`
+/// nibbles, so the digit string is the hex string with that padding
+/// trimmed. Alphanumeric-special tags are decoded as ASCII. Binary and
+/// variable tags have no further structure, so the hex is returned as-is.
+fn decode_formatted_value(format: TagFormat, hex: &str) -> String {
+    match format {
+        TagFormat::Numeric | TagFormat::CompressedNumeric => hex.trim_end_matches('F').to_string(),
+        TagFormat::AlphanumericSpecial => decode_hex(hex)
+            .map(|bytes| bytes.iter().map(|&b| b as char).collect())
+            .unwrap_or_default(),
+        TagFormat::Binary | TagFormat::Variable => hex.to_string(),
+    }
+}
+
+fn unhexify_length(slice: &[u8]) -> Result<(usize, usize), IccError> {
+    let length_in_bytes;
+    let mut shift_pointer = 1;
+    let first_length_byte = *slice.first().ok_or(IccError::UnexpectedEof)?;
     if first_length_byte < 128 { // bit8 is 0 so bit7 -> bit1 encode the length (127 max)
-        length_in_bytes = first_length_byte;
+        length_in_bytes = first_length_byte as usize;
     } else { // bit8 is 1 so the length is encoded by a number of bytes - that number is encoded by bit7 -> bit1
-
-        let extra_bytes = first_length_byte & 127;
-        let extra_length = &slice[2..2+(extra_bytes*2)];
-        if extra_length.len() < 1 {
+        let extra_bytes = (first_length_byte & 127) as usize;
+        let extra_length = slice.get(1..1+extra_bytes).ok_or(IccError::UnexpectedEof)?;
+        if extra_length.is_empty() {
             return Err(IccError::BadLength("length is missing for this tag!".into()));
         }
-        length_in_bytes = usize::from_str_radix(extra_length, 16)?;
-        shift_pointer += extra_bytes * 2;
+        length_in_bytes = extra_length.iter().fold(0_usize, |acc, b| (acc << 8) | *b as usize);
+        shift_pointer += extra_bytes;
     }
     if length_in_bytes < 1 {
         return Err(IccError::BadLength("too short".into()));
     }
-    return Ok((length_in_bytes, shift_pointer))
+    Ok((length_in_bytes, shift_pointer))
 }
 
-fn get_tag_data() -> HashMap<String, TagData> {
-    HashMap::from([
-        ("9F33".into(), TagData::new("terminalcapabilities", "9F33", 6, 6)),
-    ])
+/// The wire-format encoding of a tag's value, per EMV book 3 annex A.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum TagFormat {
+    /// BCD digits, right-padded with `F` if the digit count is odd.
+    Numeric,
+    /// BCD digits, right-padded with `F` to fill the field (no leading zeros).
+    CompressedNumeric,
+    /// Uninterpreted bytes.
+    Binary,
+    /// ASCII text restricted to EMV's "a" character set.
+    AlphanumericSpecial,
+    /// Length and content vary by application; treated as opaque bytes.
+    Variable,
 }
 
-struct TagData {
+pub struct TagData {
     name: String,
     tag: String,
+    format: TagFormat,
     max_length: usize,
     min_length: usize
 }
 
 impl TagData {
-    fn new(name: &str, tag: &str, max_length: usize, min_length: usize) -> Self {
-        Self { name: name.into(), tag: tag.into(), max_length, min_length }
+    pub fn new(name: &str, tag: &str, format: TagFormat, max_length: usize, min_length: usize) -> Self {
+        Self { name: name.into(), tag: tag.into(), format, max_length, min_length }
+    }
+}
+
+/// A registry of known tags, used to look up how to validate and decode
+/// each one. [`TagDictionary::common`] ships the usual EMV contactless
+/// tag set; callers can [`register`](TagDictionary::register) extra or
+/// overriding entries, or load a whole dictionary from JSON with
+/// [`from_json`](TagDictionary::from_json) so new tags don't need a
+/// code change.
+pub struct TagDictionary {
+    by_tag: HashMap<String, TagData>,
+    name_to_tag: HashMap<String, String>,
+}
+
+impl TagDictionary {
+    /// An empty dictionary with no registered tags.
+    pub fn new() -> Self {
+        Self { by_tag: HashMap::new(), name_to_tag: HashMap::new() }
+    }
+
+    /// The common EMV contactless tag set.
+    pub fn common() -> Self {
+        let mut dictionary = Self::new();
+        dictionary
+            .register(TagData::new("terminalcapabilities", "9F33", TagFormat::Binary, 3, 3))
+            .register(TagData::new("readrecordresponsetemplate", "70", TagFormat::Variable, 255, 0))
+            .register(TagData::new("amountauthorisednumeric", "9F02", TagFormat::Numeric, 6, 6))
+            .register(TagData::new("amountotherinumeric", "9F03", TagFormat::Numeric, 6, 6))
+            .register(TagData::new("applicationprimaryaccountnumber", "5A", TagFormat::CompressedNumeric, 10, 1))
+            .register(TagData::new("applicationexpirationdate", "5F24", TagFormat::Numeric, 3, 3))
+            .register(TagData::new("applicationinterchangeprofile", "82", TagFormat::Binary, 2, 2))
+            .register(TagData::new("terminalverificationresults", "95", TagFormat::Binary, 5, 5))
+            .register(TagData::new("terminalcountrycode", "9F1A", TagFormat::Numeric, 2, 2))
+            .register(TagData::new("transactiondate", "9A", TagFormat::Numeric, 3, 3))
+            .register(TagData::new("transactioncurrencycode", "5F2A", TagFormat::Numeric, 2, 2))
+            .register(TagData::new("applicationtransactioncounter", "9F36", TagFormat::Binary, 2, 2))
+            .register(TagData::new("unpredictablenumber", "9F37", TagFormat::Binary, 4, 4))
+            .register(TagData::new("dedicatedfilename", "84", TagFormat::Binary, 16, 5))
+            .register(TagData::new("applicationlabel", "50", TagFormat::AlphanumericSpecial, 16, 1));
+        dictionary
+    }
+
+    /// Registers a tag, overriding any existing entry for the same tag.
+    pub fn register(&mut self, tag_data: TagData) -> &mut Self {
+        self.name_to_tag.insert(tag_data.name.clone(), tag_data.tag.clone());
+        self.by_tag.insert(tag_data.tag.clone(), tag_data);
+        self
+    }
+
+    /// Looks up a tag entry by its exact wire tag (e.g. `"9F33"`).
+    pub fn get(&self, tag: &str) -> Option<&TagData> {
+        self.by_tag.get(tag)
+    }
+
+    /// Looks up a tag entry by either its wire tag or its dictionary name,
+    /// so callers that only have one or the other - such as [`encode_icc`]
+    /// fed the name-keyed output of [`parse_icc`] - don't have to care
+    /// which they're holding.
+    pub fn resolve(&self, key: &str) -> Option<&TagData> {
+        self.by_tag.get(&key.to_uppercase())
+            .or_else(|| self.name_to_tag.get(key).and_then(|tag| self.by_tag.get(tag)))
+    }
+
+    /// Builds a dictionary from a JSON array of tag definitions, e.g.
+    /// `[{"tag": "9F33", "name": "terminalcapabilities", "format": "binary", "min_length": 3, "max_length": 3}]`.
+    pub fn from_json(json: &str) -> Result<Self, IccError> {
+        #[derive(serde::Deserialize)]
+        struct TagDefinition {
+            tag: String,
+            name: String,
+            format: TagFormat,
+            min_length: usize,
+            max_length: usize,
+        }
+        let definitions: Vec<TagDefinition> = serde_json::from_str(json)
+            .map_err(|err| IccError::BadDictionary(err.to_string()))?;
+        let mut dictionary = Self::new();
+        for definition in definitions {
+            dictionary.register(TagData::new(
+                &definition.name,
+                &definition.tag,
+                definition.format,
+                definition.max_length,
+                definition.min_length,
+            ));
+        }
+        Ok(dictionary)
+    }
+}
+
+impl Default for TagDictionary {
+    fn default() -> Self {
+        Self::common()
     }
 }
 
@@ -91,8 +423,17 @@ pub enum IccError {
     #[error("InvalidHex: {0}")]
     InvalidHex(#[from] ParseIntError),
 
-    #[error("something was wrong")]
-    GenericError,
+    #[error("MaxDepthExceeded: nesting deeper than {MAX_DEPTH} constructed tags")]
+    MaxDepthExceeded,
+
+    #[error("BadDictionary: {0}")]
+    BadDictionary(String),
+
+    #[error("UnexpectedEof: ran out of input while decoding a tag, length or value")]
+    UnexpectedEof,
+
+    #[error("OddLengthHex: hex string has an odd number of characters")]
+    OddLengthHex,
 }
 
 #[cfg(test)]
@@ -101,22 +442,227 @@ mod tests {
 
     #[test]
     fn test_parse_correct_icc() {
+        let dictionary = TagDictionary::common();
         let tests = vec![
-            ("9F3303E0A8B1", vec![("terminalcapabilities", "E0A8B1")]),
+            ("9F3303E0A8B1", vec![("terminalcapabilities", IccValue::Primitive("E0A8B1".into()))]),
         ];
         for (icc_string, expected) in tests {
-            let expected: HashMap<String, String> = expected.into_iter().map(|(s1, s2): (&str, &str)| (s1.to_owned(), s2.to_owned())).collect();
-            assert_eq!(Ok(expected), parse_icc(icc_string));
+            let expected: Vec<(String, IccValue)> = expected.into_iter().map(|(s1, v): (&str, IccValue)| (s1.to_owned(), v)).collect();
+            assert_eq!(Ok(expected), parse_icc(icc_string, &dictionary));
         }
     }
 
     #[test]
     fn test_parse_incorrect_icc() {
+        let dictionary = TagDictionary::common();
         let tests = vec![
-            ("9FXX03E0A8B1", IccError::BadTag("9FXX".into())),
+            ("9F9903E0A8B1", IccError::BadTag("9F99".into())),
         ];
         for (icc_string, expected) in tests {
-            assert_eq!(Err(expected), parse_icc(icc_string));
+            assert_eq!(Err(expected), parse_icc(icc_string, &dictionary));
+        }
+    }
+
+    #[test]
+    fn test_parse_rejects_odd_length_hex() {
+        let dictionary = TagDictionary::common();
+        assert_eq!(Err(IccError::OddLengthHex), parse_icc("9F330", &dictionary));
+    }
+
+    #[test]
+    fn test_parse_rejects_non_hex_input() {
+        let dictionary = TagDictionary::common();
+        assert!(matches!(parse_icc("9FXX03E0A8B1", &dictionary), Err(IccError::InvalidHex(_))));
+    }
+
+    #[test]
+    fn test_parse_reports_unexpected_eof_instead_of_panicking() {
+        let dictionary = TagDictionary::common();
+        // declares a 3-byte value but only 1 byte follows
+        assert_eq!(Err(IccError::UnexpectedEof), parse_icc("9F3303E0", &dictionary));
+    }
+
+    #[test]
+    fn test_parse_constructed_tag_recurses_into_nested_tlv() {
+        let dictionary = TagDictionary::common();
+        let icc_string = "70069F3303E0A8B1";
+        let expected: Vec<(String, IccValue)> = vec![
+            ("readrecordresponsetemplate".to_owned(), IccValue::Constructed(vec![
+                ("terminalcapabilities".to_owned(), IccValue::Primitive("E0A8B1".into())),
+            ])),
+        ];
+        assert_eq!(Ok(expected), parse_icc(icc_string, &dictionary));
+    }
+
+    #[test]
+    fn test_parse_preserves_order_and_repeated_tags() {
+        let dictionary = TagDictionary::common();
+        let icc_string = "9F3303E0A8B19F330311AAFF"; // terminalcapabilities appears twice
+        let expected: Vec<(String, IccValue)> = vec![
+            ("terminalcapabilities".to_owned(), IccValue::Primitive("E0A8B1".into())),
+            ("terminalcapabilities".to_owned(), IccValue::Primitive("11AAFF".into())),
+        ];
+        let parsed = parse_icc(icc_string, &dictionary).unwrap();
+        assert_eq!(expected, parsed);
+        assert_eq!(Some(&IccValue::Primitive("E0A8B1".into())), first(&parsed, "terminalcapabilities"));
+        assert_eq!(Some(&IccValue::Primitive("11AAFF".into())), last(&parsed, "terminalcapabilities"));
+    }
+
+    #[test]
+    fn test_parse_respects_max_depth() {
+        let dictionary = TagDictionary::common();
+        // a constructed tag nested deeper than MAX_DEPTH should be rejected
+        // rather than overflow the stack. Built inside-out so each "70"
+        // wrapper's length actually covers the whole remaining subtree,
+        // rather than each level's value being consumed by the next
+        // header alone - otherwise recursion would hit EOF long before
+        // the depth guard is ever reached.
+        let mut icc_string = "9F3303E0A8B1".to_owned();
+        for _ in 0..MAX_DEPTH + 1 {
+            let wrapped_bytes = icc_string.len() / 2;
+            icc_string = format!("70{:02X}{}", wrapped_bytes, icc_string);
         }
+        assert_eq!(Err(IccError::MaxDepthExceeded), parse_icc(&icc_string, &dictionary));
+    }
+
+    #[test]
+    fn test_parse_enforces_min_length() {
+        let dictionary = TagDictionary::common();
+        // terminalcapabilities requires exactly 3 bytes, this gives 2
+        assert_eq!(
+            Err(IccError::BadLength("tag 9F33 value is 2 bytes, expected between 3 and 3".into())),
+            parse_icc("9F3302E0A8", &dictionary),
+        );
+    }
+
+    #[test]
+    fn test_tlv_iterator_yields_top_level_objects_lazily() {
+        let bytes = decode_hex("9F3303E0A8B19F3303AABBCC").unwrap();
+        let mut iter = TlvIterator::new(&bytes);
+        assert_eq!(iter.next().unwrap().unwrap(), ("9F33".to_owned(), &[0xE0, 0xA8, 0xB1][..]));
+        assert_eq!(iter.next().unwrap().unwrap(), ("9F33".to_owned(), &[0xAA, 0xBB, 0xCC][..]));
+        assert!(iter.next().is_none());
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_tlv_iterator_terminates_after_an_error() {
+        // trailing lone 0x9F: a multi-byte tag start with no second byte.
+        // next_element() errors without advancing pos on its own, so the
+        // iterator must stop itself or this would loop forever.
+        let bytes = decode_hex("9F3303E0A8B19F").unwrap();
+        let mut iter = TlvIterator::new(&bytes);
+        assert_eq!(iter.next().unwrap().unwrap(), ("9F33".to_owned(), &[0xE0, 0xA8, 0xB1][..]));
+        assert_eq!(iter.next().unwrap(), Err(IccError::UnexpectedEof));
+        assert!(iter.next().is_none());
+        assert_eq!(iter.collect::<Result<Vec<_>, _>>(), Ok(vec![]));
+    }
+
+    #[test]
+    fn test_tlv_iterator_can_stop_early() {
+        // truncated second header: a caller that only wants the first object
+        // never has to reach it
+        let bytes = decode_hex("9F3303E0A8B19F33").unwrap();
+        let mut iter = TlvIterator::new(&bytes);
+        assert_eq!(iter.next().unwrap().unwrap(), ("9F33".to_owned(), &[0xE0, 0xA8, 0xB1][..]));
+    }
+
+    #[test]
+    fn test_encode_primitive_tag() {
+        let dictionary = TagDictionary::common();
+        let data = vec![
+            ("9F33".to_owned(), IccValue::Primitive("E0A8B1".into())),
+        ];
+        assert_eq!(Ok("9F3303E0A8B1".to_owned()), encode_icc(&data, &dictionary));
+    }
+
+    #[test]
+    fn test_encode_constructed_tag() {
+        let dictionary = TagDictionary::common();
+        let data = vec![
+            ("70".to_owned(), IccValue::Constructed(vec![
+                ("9F33".to_owned(), IccValue::Primitive("E0A8B1".into())),
+            ])),
+        ];
+        assert_eq!(Ok("70069F3303E0A8B1".to_owned()), encode_icc(&data, &dictionary));
+    }
+
+    #[test]
+    fn test_encode_rejects_out_of_bounds_length() {
+        let dictionary = TagDictionary::common();
+        let data = vec![
+            ("9F33".to_owned(), IccValue::Primitive("E0A8".into())), // 2 bytes, needs exactly 3
+        ];
+        assert!(matches!(encode_icc(&data, &dictionary), Err(IccError::BadLength(_))));
+    }
+
+    #[test]
+    fn test_encode_preserves_order_of_repeated_tags() {
+        let dictionary = TagDictionary::common();
+        let data = vec![
+            ("9F33".to_owned(), IccValue::Primitive("E0A8B1".into())),
+            ("9F33".to_owned(), IccValue::Primitive("11AAFF".into())),
+        ];
+        assert_eq!(Ok("9F3303E0A8B19F330311AAFF".to_owned()), encode_icc(&data, &dictionary));
+    }
+
+    #[test]
+    fn test_encode_length_uses_long_form_above_127_bytes() {
+        assert_eq!("05", encode_length(5));
+        assert_eq!("7F", encode_length(127));
+        assert_eq!("8180", encode_length(128));
+        assert_eq!("81FF", encode_length(255));
+        assert_eq!("820100", encode_length(256));
+    }
+
+    #[test]
+    fn test_round_trip_parse_then_encode() {
+        // parse_icc keys elements (including nested constructed children)
+        // by dictionary name, and encode_icc resolves names as well as
+        // tags, so the parsed output can be fed straight back in.
+        let dictionary = TagDictionary::common();
+        let icc_string = "70069F3303E0A8B1";
+        let parsed = parse_icc(icc_string, &dictionary).unwrap();
+        assert_eq!(Ok(icc_string.to_owned()), encode_icc(&parsed, &dictionary));
+    }
+
+    #[test]
+    fn test_round_trip_preserves_repeated_tags() {
+        let dictionary = TagDictionary::common();
+        let icc_string = "9F3303E0A8B19F330311AAFF";
+        let parsed = parse_icc(icc_string, &dictionary).unwrap();
+        assert_eq!(Ok(icc_string.to_owned()), encode_icc(&parsed, &dictionary));
+    }
+
+    #[test]
+    fn test_decode_typed_value_trims_numeric_padding() {
+        let tag_data = TagData::new("applicationexpirationdate", "5F24", TagFormat::Numeric, 3, 3);
+        let value = IccValue::Primitive("25123F".into());
+        assert_eq!(Some("25123".to_owned()), value.decoded(&tag_data));
+    }
+
+    #[test]
+    fn test_decode_typed_value_decodes_alphanumeric_special_as_ascii() {
+        let tag_data = TagData::new("applicationlabel", "50", TagFormat::AlphanumericSpecial, 16, 1);
+        let value = IccValue::Primitive("56495341".into()); // "VISA"
+        assert_eq!(Some("VISA".to_owned()), value.decoded(&tag_data));
+    }
+
+    #[test]
+    fn test_tag_dictionary_register_overrides_existing_entry() {
+        let mut dictionary = TagDictionary::common();
+        // common() ships terminalcapabilities as exactly 3 bytes; override
+        // it to exactly 6 and confirm both that the new bounds are used
+        // (a 6-byte value now encodes where a 3-byte one used to) and that
+        // the 3-byte value the old entry would have accepted is rejected.
+        dictionary.register(TagData::new("terminalcapabilities", "9F33", TagFormat::Binary, 6, 6));
+        let data = vec![
+            ("9F33".to_owned(), IccValue::Primitive("E0A8B1E0A8B1".into())),
+        ];
+        assert_eq!(Ok("9F3306E0A8B1E0A8B1".to_owned()), encode_icc(&data, &dictionary));
+
+        let too_short = vec![
+            ("9F33".to_owned(), IccValue::Primitive("E0A8B1".into())),
+        ];
+        assert!(matches!(encode_icc(&too_short, &dictionary), Err(IccError::BadLength(_))));
+    }
+}